@@ -1,13 +1,18 @@
 use anyhow::Result;
 use base64;
+use bincode;
+use bip39::{Language, Mnemonic};
 use poem::{
     IntoResponse, Route, Server, get, handler,
     listener::TcpListener,
     post,
-    web::{Json, Path},
+    web::{Json, Path, Query},
 };
 use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountData;
 use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_client::rpc_request::TokenAccountsFilter;
 use solana_sdk::{
     bs58,
     commitment_config::CommitmentConfig,
@@ -19,8 +24,79 @@ use solana_sdk::{
 };
 use spl_token::instruction::{initialize_mint, mint_to};
 use std::str::FromStr;
+use tiny_hderive::bip32::ExtendedPrivKey;
+
+// The Solana cluster an RPC-touching handler should talk to. Defaults to the
+// `SOLANA_CLUSTER` env var (falling back to devnet) and can be overridden
+// per-request via a `?cluster=` query parameter.
+#[derive(Debug, Clone)]
+enum Cluster {
+    Testnet,
+    MainnetBeta,
+    Devnet,
+    Localnet,
+    Custom(String),
+}
+
+impl Cluster {
+    fn url(&self) -> String {
+        match self {
+            Cluster::Testnet => "https://api.testnet.solana.com".to_string(),
+            Cluster::MainnetBeta => "https://api.mainnet-beta.solana.com".to_string(),
+            Cluster::Devnet => "https://api.devnet.solana.com".to_string(),
+            Cluster::Localnet => "http://127.0.0.1:8899".to_string(),
+            Cluster::Custom(url) => url.clone(),
+        }
+    }
+}
+
+impl FromStr for Cluster {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mainnet-beta" | "mainnet" | "m" => Ok(Cluster::MainnetBeta),
+            "devnet" | "d" => Ok(Cluster::Devnet),
+            "testnet" | "t" => Ok(Cluster::Testnet),
+            "localnet" | "localhost" | "l" => Ok(Cluster::Localnet),
+            other if other.starts_with("http://") || other.starts_with("https://") => {
+                Ok(Cluster::Custom(other.to_string()))
+            }
+            other => Err(format!("Unknown cluster: {}", other)),
+        }
+    }
+}
 
-const RPC_URL: &str = "https://api.devnet.solana.com"; // Use devnet for safety
+impl Default for Cluster {
+    fn default() -> Self {
+        Cluster::Devnet
+    }
+}
+
+// Resolves the default cluster from the `SOLANA_CLUSTER` env var, falling
+// back to devnet when unset or unparseable.
+fn default_cluster() -> Cluster {
+    std::env::var("SOLANA_CLUSTER")
+        .ok()
+        .and_then(|s| Cluster::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+#[derive(Deserialize)]
+struct ClusterQuery {
+    cluster: Option<String>,
+}
+
+impl ClusterQuery {
+    // Resolves the requested cluster, falling back to the env-configured
+    // default when no `?cluster=` query parameter was supplied.
+    fn resolve(&self) -> Cluster {
+        self.cluster
+            .as_deref()
+            .and_then(|s| Cluster::from_str(s).ok())
+            .unwrap_or_else(default_cluster)
+    }
+}
 
 // All structs
 
@@ -37,6 +113,31 @@ struct KeypairResponse {
     data: KeypairData,
 }
 
+// HD keypair derivation structs
+
+#[derive(Deserialize)]
+struct DeriveKeypairRequest {
+    mnemonic: Option<String>,
+    passphrase: Option<String>,
+    path: Option<String>,
+    index: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct DeriveKeypairResponse {
+    success: bool,
+    data: Option<DeriveKeypairData>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DeriveKeypairData {
+    pubkey: String,
+    secret: String,
+    mnemonic: String,
+    derivation_path: String,
+}
+
 // structs for creating spl token
 
 #[derive(Deserialize)]
@@ -97,20 +198,90 @@ struct SignMessageData {
     message: String,
 }
 
+// verify message structs
+
+#[derive(Deserialize)]
+struct VerifyMessageRequest {
+    message: String,
+    signature: String,
+    public_key: String,
+}
+
+#[derive(Serialize)]
+struct VerifyMessageResponse {
+    success: bool,
+    data: Option<VerifyMessageData>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct VerifyMessageData {
+    valid: bool,
+    message: String,
+    public_key: String,
+}
+
 #[derive(Serialize)]
 struct BalanceResponse {
     address: String,
     balance_sol: f64,
 }
 
-#[derive(Serialize)]
-// struct TokenAccount {
-//     pubkey: String,
-// }
 #[derive(Deserialize)]
 struct SendRequest {
     to: String,
     amount: f64,
+    from_secret: Option<String>,
+    fee_payer_secret: Option<String>,
+}
+
+// transaction status structs
+
+#[derive(Deserialize)]
+struct TxStatusQuery {
+    wait: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TxStatusResponse {
+    signature: String,
+    confirmed: bool,
+    confirmation_status: Option<String>,
+    slot: Option<u64>,
+    err: Option<String>,
+}
+
+// transaction simulation / rent-exemption structs
+
+#[derive(Deserialize)]
+struct SimulateTxRequest {
+    transaction: String, // base64-encoded, bincode-serialized Transaction
+}
+
+#[derive(Serialize)]
+struct SimulateTxResponse {
+    success: bool,
+    logs: Vec<String>,
+    units_consumed: Option<u64>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RentExemptionResponse {
+    size: usize,
+    lamports: u64,
+}
+
+// token account enumeration structs
+
+#[derive(Serialize)]
+struct TokenAccount {
+    account_pubkey: String,
+    mint: String,
+    owner: String,
+    amount: u64,
+    decimals: u8,
+    ui_amount: f64,
 }
 
 // ========== HANDLERS ==========
@@ -129,6 +300,82 @@ async fn generate_keypair() -> impl IntoResponse {
     })
 }
 
+// HD keypair derivation endpoint
+#[handler]
+async fn derive_keypair(Json(req): Json<DeriveKeypairRequest>) -> Json<DeriveKeypairResponse> {
+    let mnemonic = match &req.mnemonic {
+        Some(phrase) => match Mnemonic::parse_in_normalized(Language::English, phrase) {
+            Ok(m) => m,
+            Err(_) => {
+                return Json(DeriveKeypairResponse {
+                    success: false,
+                    data: None,
+                    error: Some("Invalid mnemonic phrase".to_string()),
+                });
+            }
+        },
+        None => Mnemonic::generate_in(Language::English, 12).expect("mnemonic generation"),
+    };
+
+    let passphrase = req.passphrase.as_deref().unwrap_or("");
+    let seed = mnemonic.to_seed(passphrase);
+
+    let index = req.index.unwrap_or(0);
+    let derivation_path = req
+        .path
+        .clone()
+        .unwrap_or_else(|| format!("m/44'/501'/{}'/0'", index));
+
+    let child = match ExtendedPrivKey::derive(&seed, derivation_path.as_str()) {
+        Ok(key) => key,
+        Err(_) => {
+            return Json(DeriveKeypairResponse {
+                success: false,
+                data: None,
+                error: Some("Failed to derive key from path".to_string()),
+            });
+        }
+    };
+
+    let secret_key = match ed25519_dalek::SecretKey::from_bytes(&child.secret()) {
+        Ok(sk) => sk,
+        Err(_) => {
+            return Json(DeriveKeypairResponse {
+                success: false,
+                data: None,
+                error: Some("Failed to expand derived secret key".to_string()),
+            });
+        }
+    };
+    let public_key = ed25519_dalek::PublicKey::from(&secret_key);
+
+    let mut keypair_bytes = [0u8; 64];
+    keypair_bytes[..32].copy_from_slice(secret_key.as_bytes());
+    keypair_bytes[32..].copy_from_slice(public_key.as_bytes());
+
+    let keypair = match Keypair::from_bytes(&keypair_bytes) {
+        Ok(kp) => kp,
+        Err(_) => {
+            return Json(DeriveKeypairResponse {
+                success: false,
+                data: None,
+                error: Some("Failed to construct keypair from derived seed".to_string()),
+            });
+        }
+    };
+
+    Json(DeriveKeypairResponse {
+        success: true,
+        data: Some(DeriveKeypairData {
+            pubkey: keypair.pubkey().to_string(),
+            secret: bs58::encode(keypair.to_bytes()).into_string(),
+            mnemonic: mnemonic.to_string(),
+            derivation_path,
+        }),
+        error: None,
+    })
+}
+
 // create token endpoint
 #[handler]
 async fn create_token(Json(req): Json<TokenCreateRequest>) -> Json<TokenInstructionResponse> {
@@ -291,8 +538,67 @@ async fn sign_message(Json(req): Json<SignMessageRequest>) -> Json<SignMessageRe
 }
 
 #[handler]
-async fn get_balance(Path(address): Path<String>) -> Json<BalanceResponse> {
-    let client = RpcClient::new(RPC_URL.to_string());
+async fn verify_message(Json(req): Json<VerifyMessageRequest>) -> Json<VerifyMessageResponse> {
+    if req.message.is_empty() || req.signature.is_empty() || req.public_key.is_empty() {
+        return Json(VerifyMessageResponse {
+            success: false,
+            data: None,
+            error: Some("Missing required fields".to_string()),
+        });
+    }
+
+    let pubkey = match Pubkey::from_str(&req.public_key) {
+        Ok(pk) => pk,
+        Err(_) => {
+            return Json(VerifyMessageResponse {
+                success: false,
+                data: None,
+                error: Some("Invalid public key format".to_string()),
+            });
+        }
+    };
+
+    let signature_bytes = match base64::decode(&req.signature) {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return Json(VerifyMessageResponse {
+                success: false,
+                data: None,
+                error: Some("Invalid signature format".to_string()),
+            });
+        }
+    };
+
+    let signature = match Signature::try_from(signature_bytes.as_slice()) {
+        Ok(sig) => sig,
+        Err(_) => {
+            return Json(VerifyMessageResponse {
+                success: false,
+                data: None,
+                error: Some("Invalid signature length".to_string()),
+            });
+        }
+    };
+
+    let valid = signature.verify(pubkey.as_ref(), req.message.as_bytes());
+
+    Json(VerifyMessageResponse {
+        success: true,
+        data: Some(VerifyMessageData {
+            valid,
+            message: req.message,
+            public_key: req.public_key,
+        }),
+        error: None,
+    })
+}
+
+#[handler]
+async fn get_balance(
+    Path(address): Path<String>,
+    Query(cluster): Query<ClusterQuery>,
+) -> Json<BalanceResponse> {
+    let client = RpcClient::new(cluster.resolve().url());
 
     let pubkey = match Pubkey::from_str(&address) {
         Ok(pk) => pk,
@@ -313,47 +619,255 @@ async fn get_balance(Path(address): Path<String>) -> Json<BalanceResponse> {
     })
 }
 
-// #[handler]
-// async fn get_nfts(Path(address): Path<String>) -> Json<Vec<TokenAccount>> {
-//     let client = RpcClient::new(RPC_URL.to_string());
-
-//     let owner = match Pubkey::from_str(&address) {
-//         Ok(pk) => pk,
-//         Err(_) => return Json(vec![]),
-//     };
-
-//     let result = client.get_token_accounts_by_owner(
-//         &owner,
-//         solana_client::rpc_config::RpcTokenAccountsFilter::ProgramId(
-//             Pubkey::from_str("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA").unwrap(),
-//         ),
-//     );
-
-//     match result {
-//         Ok(accs) => {
-//             let tokens = accs
-//                 .into_iter()
-//                 .map(|acc| TokenAccount { pubkey: acc.pubkey })
-//                 .collect();
-//             Json(tokens)
-//         }
-//         Err(_) => Json(vec![]),
-//     }
-// }
+#[handler]
+async fn get_token_accounts(
+    Path(address): Path<String>,
+    Query(cluster): Query<ClusterQuery>,
+) -> Json<Vec<TokenAccount>> {
+    let client = RpcClient::new(cluster.resolve().url());
+
+    let owner = match Pubkey::from_str(&address) {
+        Ok(pk) => pk,
+        Err(_) => return Json(vec![]),
+    };
+
+    // get_token_accounts_by_owner always decodes with UiAccountEncoding::JsonParsed,
+    // so each account's data comes back as an already-parsed SPL token account.
+    let result =
+        client.get_token_accounts_by_owner(&owner, TokenAccountsFilter::ProgramId(spl_token::id()));
+
+    let accounts = match result {
+        Ok(accs) => accs,
+        Err(_) => return Json(vec![]),
+    };
+
+    let tokens = accounts
+        .into_iter()
+        .filter_map(|keyed_account| {
+            let parsed = match keyed_account.account.data {
+                UiAccountData::Json(parsed_account) => parsed_account.parsed,
+                _ => return None,
+            };
+            let info = parsed.get("info")?;
+            let token_amount = info.get("tokenAmount")?;
+
+            Some(TokenAccount {
+                account_pubkey: keyed_account.pubkey,
+                mint: info.get("mint")?.as_str()?.to_string(),
+                owner: info.get("owner")?.as_str()?.to_string(),
+                amount: token_amount.get("amount")?.as_str()?.parse().ok()?,
+                decimals: token_amount.get("decimals")?.as_u64()? as u8,
+                ui_amount: token_amount
+                    .get("uiAmount")
+                    .and_then(|v| v.as_f64())
+                    .unwrap_or(0.0),
+            })
+        })
+        .collect();
+
+    Json(tokens)
+}
+
+// how many times to poll get_signature_statuses when `?wait=` is requested
+const CONFIRMATION_POLL_ATTEMPTS: u32 = 20;
+const CONFIRMATION_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+#[handler]
+async fn get_tx_status(
+    Path(signature): Path<String>,
+    Query(cluster): Query<ClusterQuery>,
+    Query(query): Query<TxStatusQuery>,
+) -> Json<TxStatusResponse> {
+    let client = RpcClient::new(cluster.resolve().url());
+
+    let sig = match Signature::from_str(&signature) {
+        Ok(sig) => sig,
+        Err(_) => {
+            return Json(TxStatusResponse {
+                signature,
+                confirmed: false,
+                confirmation_status: None,
+                slot: None,
+                err: Some("Invalid signature format".to_string()),
+            });
+        }
+    };
+
+    let target_commitment = query.wait.as_deref();
+
+    for attempt in 0..CONFIRMATION_POLL_ATTEMPTS.max(1) {
+        let statuses = match client.get_signature_statuses(&[sig]) {
+            Ok(resp) => resp.value,
+            Err(e) => {
+                return Json(TxStatusResponse {
+                    signature: signature.clone(),
+                    confirmed: false,
+                    confirmation_status: None,
+                    slot: None,
+                    err: Some(e.to_string()),
+                });
+            }
+        };
+
+        match statuses.into_iter().next().flatten() {
+            Some(status) => {
+                let confirmation_status = status
+                    .confirmation_status
+                    .as_ref()
+                    .map(|s| format!("{:?}", s).to_lowercase());
+
+                let reached_target = match target_commitment {
+                    Some(target) => confirmation_status.as_deref() == Some(target),
+                    None => true,
+                };
+
+                if reached_target
+                    || attempt + 1 == CONFIRMATION_POLL_ATTEMPTS
+                    || query.wait.is_none()
+                {
+                    return Json(TxStatusResponse {
+                        signature,
+                        confirmed: status.err.is_none(),
+                        confirmation_status,
+                        slot: Some(status.slot),
+                        err: status.err.map(|e| e.to_string()),
+                    });
+                }
+            }
+            None if query.wait.is_none() => {
+                return Json(TxStatusResponse {
+                    signature,
+                    confirmed: false,
+                    confirmation_status: None,
+                    slot: None,
+                    err: None,
+                });
+            }
+            None => {}
+        }
+
+        tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+    }
+
+    Json(TxStatusResponse {
+        signature,
+        confirmed: false,
+        confirmation_status: None,
+        slot: None,
+        err: Some("Timed out waiting for confirmation".to_string()),
+    })
+}
 
 #[handler]
-async fn send_sol(Json(body): Json<SendRequest>) -> Json<String> {
+async fn simulate_tx(
+    Json(req): Json<SimulateTxRequest>,
+    Query(cluster): Query<ClusterQuery>,
+) -> Json<SimulateTxResponse> {
+    let client = RpcClient::new(cluster.resolve().url());
+
+    let tx_bytes = match base64::decode(&req.transaction) {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return Json(SimulateTxResponse {
+                success: false,
+                logs: vec![],
+                units_consumed: None,
+                error: Some("Invalid base64 transaction".to_string()),
+            });
+        }
+    };
+
+    let tx: Transaction = match bincode::deserialize(&tx_bytes) {
+        Ok(tx) => tx,
+        Err(_) => {
+            return Json(SimulateTxResponse {
+                success: false,
+                logs: vec![],
+                units_consumed: None,
+                error: Some("Failed to decode transaction".to_string()),
+            });
+        }
+    };
+
+    let config = RpcSimulateTransactionConfig {
+        sig_verify: false,
+        ..RpcSimulateTransactionConfig::default()
+    };
+
+    match client.simulate_transaction_with_config(&tx, config) {
+        Ok(resp) => {
+            let result = resp.value;
+            Json(SimulateTxResponse {
+                success: result.err.is_none(),
+                logs: result.logs.unwrap_or_default(),
+                units_consumed: result.units_consumed,
+                error: result.err.map(|e| e.to_string()),
+            })
+        }
+        Err(e) => Json(SimulateTxResponse {
+            success: false,
+            logs: vec![],
+            units_consumed: None,
+            error: Some(e.to_string()),
+        }),
+    }
+}
+
+// lets callers learn the lamports needed to make a new mint or token account
+// rent-exempt before funding it, ahead of create_token/mint_token
+#[handler]
+async fn get_rent_exemption(
+    Path(size): Path<usize>,
+    Query(cluster): Query<ClusterQuery>,
+) -> Json<RentExemptionResponse> {
+    let client = RpcClient::new(cluster.resolve().url());
+
+    let lamports = client
+        .get_minimum_balance_for_rent_exemption(size)
+        .unwrap_or(0);
+
+    Json(RentExemptionResponse { size, lamports })
+}
+
+// Decodes a bs58 secret key into a Keypair, for requests that supply their
+// own signer instead of relying on the local id.json.
+fn keypair_from_secret(secret: &str) -> std::result::Result<Keypair, &'static str> {
+    let bytes = bs58::decode(secret)
+        .into_vec()
+        .map_err(|_| "Invalid secret key format")?;
+    Keypair::from_bytes(&bytes).map_err(|_| "Invalid secret key length")
+}
+
+#[handler]
+async fn send_sol(
+    Json(body): Json<SendRequest>,
+    Query(cluster): Query<ClusterQuery>,
+) -> Json<String> {
     let to_pubkey = match Pubkey::from_str(&body.to) {
         Ok(pk) => pk,
         Err(_) => return Json("Invalid recipient pubkey.".to_string()),
     };
 
-    let from_keypair = match read_keypair_file("id.json") {
-        Ok(kp) => kp,
-        Err(_) => return Json("Could not load sender keypair.".to_string()),
+    let from_keypair = match &body.from_secret {
+        Some(secret) => match keypair_from_secret(secret) {
+            Ok(kp) => kp,
+            Err(e) => return Json(format!("Invalid from_secret: {}", e)),
+        },
+        None => match read_keypair_file("id.json") {
+            Ok(kp) => kp,
+            Err(_) => return Json("Could not load sender keypair.".to_string()),
+        },
     };
 
-    let client = RpcClient::new(RPC_URL.to_string());
+    let fee_payer_keypair = match &body.fee_payer_secret {
+        Some(secret) => match keypair_from_secret(secret) {
+            Ok(kp) => Some(kp),
+            Err(e) => return Json(format!("Invalid fee_payer_secret: {}", e)),
+        },
+        None => None,
+    };
+
+    let client = RpcClient::new(cluster.resolve().url());
 
     let lamports = (body.amount * 1_000_000_000.0) as u64;
     let recent_blockhash = match client.get_latest_blockhash() {
@@ -361,16 +875,24 @@ async fn send_sol(Json(body): Json<SendRequest>) -> Json<String> {
         Err(_) => return Json("Failed to get blockhash.".to_string()),
     };
 
-    let tx = Transaction::new_signed_with_payer(
-        &[system_instruction::transfer(
-            &from_keypair.pubkey(),
-            &to_pubkey,
-            lamports,
-        )],
-        Some(&from_keypair.pubkey()),
-        &[&from_keypair],
-        recent_blockhash,
-    );
+    let instruction = system_instruction::transfer(&from_keypair.pubkey(), &to_pubkey, lamports);
+
+    let tx = match &fee_payer_keypair {
+        Some(fee_payer) if fee_payer.pubkey() != from_keypair.pubkey() => {
+            Transaction::new_signed_with_payer(
+                &[instruction],
+                Some(&fee_payer.pubkey()),
+                &[&from_keypair, fee_payer],
+                recent_blockhash,
+            )
+        }
+        _ => Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&from_keypair.pubkey()),
+            &[&from_keypair],
+            recent_blockhash,
+        ),
+    };
 
     match client.send_and_confirm_transaction(&tx) {
         Ok(sig) => Json(format!("Success! Tx Signature: {}", sig)),
@@ -379,8 +901,12 @@ async fn send_sol(Json(body): Json<SendRequest>) -> Json<String> {
 }
 
 #[handler]
-async fn airdrop_sol(Path(address): Path<String>) -> Json<String> {
-    let rpc = RpcClient::new_with_commitment(RPC_URL.to_string(), CommitmentConfig::confirmed());
+async fn airdrop_sol(
+    Path(address): Path<String>,
+    Query(cluster): Query<ClusterQuery>,
+) -> Json<String> {
+    let rpc =
+        RpcClient::new_with_commitment(cluster.resolve().url(), CommitmentConfig::confirmed());
 
     let pubkey = match Pubkey::from_str(&address) {
         Ok(pk) => pk,
@@ -399,13 +925,18 @@ async fn airdrop_sol(Path(address): Path<String>) -> Json<String> {
 async fn main() -> Result<(), std::io::Error> {
     let app = Route::new()
         .at("/balance/:address", get(get_balance))
-        // .at("/nfts/:address", get(get_nfts))
+        .at("/tokens/:address", get(get_token_accounts))
+        .at("/tx/:signature", get(get_tx_status))
+        .at("/tx/simulate", post(simulate_tx))
+        .at("/rent-exemption/:size", get(get_rent_exemption))
         .at("/send", post(send_sol))
         .at("/airdrop/:address", get(airdrop_sol))
         .at("/keypair", get(generate_keypair))
+        .at("/keypair/derive", post(derive_keypair))
         .at("/token/create", post(create_token))
         .at("/token/mint", post(mint_token))
-        .at("/message/sign", post(sign_message));
+        .at("/message/sign", post(sign_message))
+        .at("/message/verify", post(verify_message));
 
     println!("ðŸš€ Server running on http://localhost:3000");
     Server::new(TcpListener::bind("127.0.0.1:3000"))